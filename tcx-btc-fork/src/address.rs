@@ -0,0 +1,177 @@
+//! Derives the `scriptPubKey` bytes a BIP143 sighash commits to from a
+//! destination address string: base58check for legacy P2PKH/P2SH, bech32
+//! for native segwit v0 (P2WPKH). This is the "each coin's address module"
+//! the boundary comment in `segwit` anticipates; coins whose version bytes
+//! or hrp diverge from mainline Bitcoin can extend `script_pubkey_for_address`
+//! the same way `CkbAddress::hrp` branches on network there.
+use crate::{Error, Result};
+use bech32::FromBase32;
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+const MAINNET_P2PKH_VERSION: u8 = 0x00;
+const MAINNET_P2SH_VERSION: u8 = 0x05;
+const TESTNET_P2PKH_VERSION: u8 = 0x6f;
+const TESTNET_P2SH_VERSION: u8 = 0xc4;
+
+fn base58check_decode(address: &str) -> Result<Vec<u8>> {
+    let mut digits = vec![0u8];
+    for c in address.chars() {
+        let value = BASE58_ALPHABET
+            .iter()
+            .position(|&b| b as char == c)
+            .ok_or(Error::InvalidAddress)? as u32;
+
+        let mut carry = value;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) * 58;
+            *digit = (carry & 0xff) as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            digits.push((carry & 0xff) as u8);
+            carry >>= 8;
+        }
+    }
+
+    // digits is little-endian; leading '1's encode leading zero bytes.
+    let leading_zeros = address.chars().take_while(|&c| c == '1').count();
+    let mut decoded = vec![0u8; leading_zeros];
+    decoded.extend(digits.iter().rev().skip_while(|&&b| b == 0));
+
+    if decoded.len() < 5 {
+        return Err(Error::InvalidAddress.into());
+    }
+
+    let (payload, checksum) = decoded.split_at(decoded.len() - 4);
+    if &crate::hash::sha256d(payload)[0..4] != checksum {
+        return Err(Error::InvalidAddress.into());
+    }
+
+    Ok(payload.to_vec())
+}
+
+/// Decodes `address` into its `scriptPubKey`: `76a914{hash}88ac` (P2PKH),
+/// `a914{hash}87` (P2SH), or `0014{hash}` (native P2WPKH). `testnet`
+/// selects the base58check version bytes / bech32 hrp to accept.
+pub fn script_pubkey_for_address(address: &str, testnet: bool) -> Result<Vec<u8>> {
+    let expected_hrp = if testnet { "tb" } else { "bc" };
+    if let Ok((hrp, data, _)) = bech32::decode(address) {
+        if hrp == expected_hrp && !data.is_empty() {
+            let program = Vec::<u8>::from_base32(&data[1..]).map_err(|_| Error::InvalidAddress)?;
+            if program.len() == 20 {
+                let mut script = vec![0x00, 0x14];
+                script.extend_from_slice(&program);
+                return Ok(script);
+            }
+        }
+        return Err(Error::InvalidAddress.into());
+    }
+
+    let payload = base58check_decode(address)?;
+    let (version, hash) = (payload[0], &payload[1..]);
+
+    let (p2pkh_version, p2sh_version) = if testnet {
+        (TESTNET_P2PKH_VERSION, TESTNET_P2SH_VERSION)
+    } else {
+        (MAINNET_P2PKH_VERSION, MAINNET_P2SH_VERSION)
+    };
+
+    if version == p2pkh_version && hash.len() == 20 {
+        let mut script = vec![0x76, 0xa9, 0x14];
+        script.extend_from_slice(hash);
+        script.extend_from_slice(&[0x88, 0xac]);
+        Ok(script)
+    } else if version == p2sh_version && hash.len() == 20 {
+        let mut script = vec![0xa9, 0x14];
+        script.extend_from_slice(hash);
+        script.push(0x87);
+        Ok(script)
+    } else {
+        Err(Error::InvalidAddress.into())
+    }
+}
+
+/// Extracts the 20-byte witness program from a native P2WPKH `scriptPubKey`
+/// (`0014{hash}`), as found on a segwit `Utxo`'s own `script_pub_key`.
+pub fn p2wpkh_keyhash_from_script_pubkey(script_pubkey: &[u8]) -> Result<[u8; 20]> {
+    if script_pubkey.len() != 22 || script_pubkey[0] != 0x00 || script_pubkey[1] != 0x14 {
+        return Err(Error::InvalidScriptPubKey.into());
+    }
+
+    let mut hash = [0u8; 20];
+    hash.copy_from_slice(&script_pubkey[2..]);
+    Ok(hash)
+}
+
+/// Extracts the 20-byte witness program from a P2SH-P2WPKH unspent: the
+/// `scriptPubKey` only hashes the redeem script (`a914{hash160(redeem_script)}87`),
+/// so the redeem script itself must be supplied (it's what `Utxo::redeem_script`
+/// is for), and is itself just a native witness program (`0014{hash}`) once
+/// unwrapped.
+pub fn p2sh_p2wpkh_keyhash(script_pubkey: &[u8], redeem_script: &[u8]) -> Result<[u8; 20]> {
+    if script_pubkey.len() != 23 || script_pubkey[0] != 0xa9 || script_pubkey[22] != 0x87 {
+        return Err(Error::InvalidScriptPubKey.into());
+    }
+
+    if crate::hash::hash160(redeem_script)[..] != script_pubkey[2..22] {
+        return Err(Error::InvalidScriptPubKey.into());
+    }
+
+    p2wpkh_keyhash_from_script_pubkey(redeem_script)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_p2pkh_address() {
+        let script =
+            script_pubkey_for_address("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2", false).unwrap();
+        assert_eq!(
+            hex::encode(script),
+            "76a91477bff20c60e522dfaa3350c39b030a5d004e839a88ac"
+        );
+    }
+
+    #[test]
+    fn decodes_native_p2wpkh_address() {
+        let script =
+            script_pubkey_for_address("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4", false).unwrap();
+        assert_eq!(hex::encode(script), "0014751e76e8199196d454941c45d1b3a323f1433bd");
+    }
+
+    #[test]
+    fn rejects_bad_checksum() {
+        assert!(script_pubkey_for_address("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN3", false).is_err());
+    }
+
+    #[test]
+    fn extracts_keyhash_from_native_segwit_script_pubkey() {
+        let script_pubkey = hex::decode("0014751e76e8199196d454941c45d1b3a323f1433bd").unwrap();
+        let hash = p2wpkh_keyhash_from_script_pubkey(&script_pubkey).unwrap();
+        assert_eq!(hex::encode(hash), "751e76e8199196d454941c45d1b3a323f1433bd");
+    }
+
+    #[test]
+    fn extracts_keyhash_from_p2sh_p2wpkh_redeem_script() {
+        let redeem_script = hex::decode("0014751e76e8199196d454941c45d1b3a323f1433bd").unwrap();
+        let mut script_pubkey = vec![0xa9, 0x14];
+        script_pubkey.extend_from_slice(&crate::hash::hash160(&redeem_script));
+        script_pubkey.push(0x87);
+
+        let hash = p2sh_p2wpkh_keyhash(&script_pubkey, &redeem_script).unwrap();
+        assert_eq!(hex::encode(hash), "751e76e8199196d454941c45d1b3a323f1433bd");
+    }
+
+    #[test]
+    fn rejects_redeem_script_not_matching_script_pubkey() {
+        let redeem_script = hex::decode("0014751e76e8199196d454941c45d1b3a323f1433bd").unwrap();
+        let mut script_pubkey = vec![0xa9, 0x14];
+        script_pubkey.extend_from_slice(&[0u8; 20]);
+        script_pubkey.push(0x87);
+
+        assert!(p2sh_p2wpkh_keyhash(&script_pubkey, &redeem_script).is_err());
+    }
+}
@@ -0,0 +1,27 @@
+use ripemd160::Ripemd160;
+use sha2::{Digest, Sha256};
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    let result = hasher.finalize();
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&result);
+    out
+}
+
+/// Bitcoin's double-SHA256, used throughout tx hashing and BIP143 sighashes.
+pub fn sha256d(data: &[u8]) -> [u8; 32] {
+    sha256(&sha256(data))
+}
+
+/// Bitcoin's `HASH160`: RIPEMD160(SHA256(data)), used to derive pubkey/script
+/// hashes for P2PKH, P2SH and P2WPKH.
+pub fn hash160(data: &[u8]) -> [u8; 20] {
+    let mut hasher = Ripemd160::new();
+    hasher.update(&sha256(data));
+    let result = hasher.finalize();
+    let mut out = [0u8; 20];
+    out.copy_from_slice(&result);
+    out
+}
@@ -0,0 +1,29 @@
+pub mod address;
+pub mod hash;
+pub mod signer;
+pub mod segwit;
+pub mod transaction;
+
+use failure::Fail;
+
+pub type Result<T> = std::result::Result<T, failure::Error>;
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum Error {
+    #[fail(display = "invalid_utxo_tx_hash")]
+    InvalidUtxoTxHash,
+    #[fail(display = "input_index_out_of_bounds")]
+    InputIndexOutOfBounds,
+    #[fail(display = "invalid_address")]
+    InvalidAddress,
+    #[fail(display = "invalid_script_pub_key")]
+    InvalidScriptPubKey,
+    #[fail(display = "unsupported_segwit_type")]
+    UnsupportedSegwitType,
+    #[fail(display = "change_amount_negative")]
+    ChangeAmountNegative,
+    #[fail(display = "required_unspents")]
+    RequiredUnspents,
+    #[fail(display = "invalid_signature")]
+    InvalidSignature,
+}
@@ -0,0 +1,209 @@
+//! BIP143 segwit v0 sighash (native P2WPKH and P2SH-P2WPKH). Building the
+//! destination/change output scripts from address strings is fork-specific
+//! (base58check vs. bech32, per-coin version bytes) and lives in each coin's
+//! address module rather than here; `hash_outputs` below takes already
+//! resolved `SighashOutput`s so this module stays coin-agnostic.
+use crate::hash::sha256d;
+use crate::transaction::Utxo;
+use crate::{Error, Result};
+use byteorder::{LittleEndian, WriteBytesExt};
+
+pub struct SighashOutpoint {
+    /// txid in internal (little-endian / reversed-from-display) byte order.
+    pub tx_hash: [u8; 32],
+    pub vout: u32,
+}
+
+pub struct SighashInput {
+    pub outpoint: SighashOutpoint,
+    pub sequence: u32,
+}
+
+pub struct SighashOutput {
+    pub value: u64,
+    pub script_pubkey: Vec<u8>,
+}
+
+pub(crate) fn compact_size(len: usize) -> Vec<u8> {
+    let mut buf = vec![];
+    if len < 0xfd {
+        buf.push(len as u8);
+    } else if len <= 0xffff {
+        buf.push(0xfd);
+        buf.write_u16::<LittleEndian>(len as u16).unwrap();
+    } else {
+        buf.push(0xfe);
+        buf.write_u32::<LittleEndian>(len as u32).unwrap();
+    }
+    buf
+}
+
+pub(crate) fn serialize_outpoint(outpoint: &SighashOutpoint) -> Vec<u8> {
+    let mut buf = outpoint.tx_hash.to_vec();
+    buf.write_u32::<LittleEndian>(outpoint.vout).unwrap();
+    buf
+}
+
+fn hash_prevouts(inputs: &[SighashInput]) -> [u8; 32] {
+    let mut buf = vec![];
+    for input in inputs.iter() {
+        buf.extend(serialize_outpoint(&input.outpoint));
+    }
+    sha256d(&buf)
+}
+
+fn hash_sequence(inputs: &[SighashInput]) -> [u8; 32] {
+    let mut buf = vec![];
+    for input in inputs.iter() {
+        buf.write_u32::<LittleEndian>(input.sequence).unwrap();
+    }
+    sha256d(&buf)
+}
+
+pub(crate) fn serialize_output(output: &SighashOutput) -> Vec<u8> {
+    let mut buf = vec![];
+    buf.write_u64::<LittleEndian>(output.value).unwrap();
+    buf.extend(compact_size(output.script_pubkey.len()));
+    buf.extend(&output.script_pubkey);
+    buf
+}
+
+fn hash_outputs(outputs: &[SighashOutput]) -> [u8; 32] {
+    let mut buf = vec![];
+    for output in outputs.iter() {
+        buf.extend(serialize_output(output));
+    }
+    sha256d(&buf)
+}
+
+/// The P2WPKH `scriptCode` substituted in for `script_pubkey` when signing:
+/// `0x19 76 a9 14 {20-byte pubkey hash} 88 ac` (a length-prefixed classic
+/// P2PKH script). P2SH-P2WPKH uses the same scriptCode, keyed off the
+/// embedded witness program's pubkey hash rather than the P2SH script hash.
+pub fn p2wpkh_script_code(pubkey_hash: &[u8; 20]) -> Vec<u8> {
+    let mut script = vec![0x19, 0x76, 0xa9, 0x14];
+    script.extend_from_slice(pubkey_hash);
+    script.extend_from_slice(&[0x88, 0xac]);
+    script
+}
+
+/// Computes the BIP143 sighash for input `input_index`, committing to that
+/// input's own `amount` (the fix for legacy sighash's fee-forgery exposure,
+/// since every input's value is bound into the signature).
+pub fn bip143_sighash(
+    version: u32,
+    inputs: &[SighashInput],
+    outputs: &[SighashOutput],
+    input_index: usize,
+    script_code: &[u8],
+    amount: u64,
+    locktime: u32,
+    sighash_type: u32,
+) -> Result<[u8; 32]> {
+    let input = inputs
+        .get(input_index)
+        .ok_or(Error::InputIndexOutOfBounds)?;
+
+    let mut preimage = vec![];
+    preimage.write_u32::<LittleEndian>(version).unwrap();
+    preimage.extend(hash_prevouts(inputs));
+    preimage.extend(hash_sequence(inputs));
+    preimage.extend(serialize_outpoint(&input.outpoint));
+    preimage.extend(script_code);
+    preimage.write_u64::<LittleEndian>(amount).unwrap();
+    preimage.write_u32::<LittleEndian>(input.sequence).unwrap();
+    preimage.extend(hash_outputs(outputs));
+    preimage.write_u32::<LittleEndian>(locktime).unwrap();
+    preimage.write_u32::<LittleEndian>(sighash_type).unwrap();
+
+    Ok(sha256d(&preimage))
+}
+
+/// Builds the `SighashInput` list for a `BtcForkTxInput`'s unspents, reading
+/// each `Utxo`'s `tx_hash`/`vout`/`sequence` so callers don't have to
+/// re-derive the prevout ordering `hash_prevouts`/`hash_sequence` commit to.
+pub fn sighash_inputs(unspents: &[Utxo]) -> Result<Vec<SighashInput>> {
+    unspents
+        .iter()
+        .map(|utxo| {
+            let mut tx_hash = hex::decode(&utxo.tx_hash).map_err(|_| Error::InvalidUtxoTxHash)?;
+            if tx_hash.len() != 32 {
+                return Err(Error::InvalidUtxoTxHash.into());
+            }
+            // the wallet-displayed txid is big-endian; the serialized
+            // outpoint wants it reversed into internal byte order.
+            tx_hash.reverse();
+
+            let mut array = [0u8; 32];
+            array.copy_from_slice(&tx_hash);
+
+            Ok(SighashInput {
+                outpoint: SighashOutpoint {
+                    tx_hash: array,
+                    vout: utxo.vout as u32,
+                },
+                sequence: utxo.sequence as u32,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bip143_sighash_matches_reference_double_sha256() {
+        // a two-input, one-output toy transaction; the expected digest below
+        // was cross-checked against an independent double-SHA256 of the same
+        // serialized preimage.
+        let inputs = vec![
+            SighashInput {
+                outpoint: SighashOutpoint {
+                    tx_hash: [0x11; 32],
+                    vout: 0,
+                },
+                sequence: 0xffffffff,
+            },
+            SighashInput {
+                outpoint: SighashOutpoint {
+                    tx_hash: [0x22; 32],
+                    vout: 1,
+                },
+                sequence: 0xffffffff,
+            },
+        ];
+
+        let mut script_pubkey = vec![0x00, 0x14];
+        script_pubkey.extend_from_slice(&[0x33; 20]);
+        let outputs = vec![SighashOutput {
+            value: 100_000_000,
+            script_pubkey,
+        }];
+
+        let script_code = p2wpkh_script_code(&[0x44; 20]);
+
+        let sighash = bip143_sighash(
+            1,
+            &inputs,
+            &outputs,
+            0,
+            &script_code,
+            50_000_000,
+            0,
+            1, // SIGHASH_ALL
+        )
+        .unwrap();
+
+        assert_eq!(
+            hex::encode(sighash),
+            "ab51f51df536800cf942d75d90e30193f92904caa13e223c6d7d10ed5b31e008"
+        );
+    }
+
+    #[test]
+    fn bip143_sighash_rejects_out_of_bounds_input_index() {
+        let err = bip143_sighash(1, &[], &[], 0, &[], 0, 0, 1);
+        assert!(err.is_err());
+    }
+}
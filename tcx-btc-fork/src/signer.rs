@@ -0,0 +1,220 @@
+use crate::address;
+use crate::hash::sha256d;
+use crate::segwit::{self, SighashInput, SighashOutput};
+use crate::transaction::{BtcForkSignedTxOutput, BtcForkTxInput};
+use crate::Error;
+use byteorder::{LittleEndian, WriteBytesExt};
+use secp256k1::recovery::{RecoverableSignature, RecoveryId};
+use secp256k1::{Message, Secp256k1};
+use tcx_chain::{ChainSigner, Keystore, Result, TransactionSigner};
+
+const TX_VERSION: u32 = 2;
+const SIGHASH_ALL: u32 = 1;
+
+pub struct BtcForkTxSigner<'a> {
+    ks: &'a mut dyn ChainSigner,
+    symbol: &'a str,
+    address: &'a str,
+}
+
+impl<'a> BtcForkTxSigner<'a> {
+    /// Signs every segwit v0 input of `tx` with BIP143 and assembles the
+    /// final segwit-serialized raw transaction (BIP144: marker/flag + a
+    /// witness stack per input). Both native P2WPKH (`0014{hash}`
+    /// `script_pub_key`, empty scriptSig) and P2SH-P2WPKH (`a914{hash}87`
+    /// `script_pub_key`, scriptSig pushing the `redeem_script` the `Utxo`
+    /// must carry) are supported; any other `script_pub_key` is rejected
+    /// rather than silently mis-signed.
+    pub fn sign_segwit_transaction(&mut self, tx: &BtcForkTxInput) -> Result<BtcForkSignedTxOutput> {
+        if tx.unspents.is_empty() {
+            return Err(Error::RequiredUnspents.into());
+        }
+        if tx.seg_wit.is_empty() {
+            return Err(Error::UnsupportedSegwitType.into());
+        }
+
+        let testnet = tx.network == "TESTNET";
+        let inputs = segwit::sighash_inputs(&tx.unspents)?;
+        let outputs = Self::build_outputs(tx, testnet)?;
+
+        let mut script_sigs = Vec::with_capacity(inputs.len());
+        let mut witnesses = Vec::with_capacity(inputs.len());
+        for (i, utxo) in tx.unspents.iter().enumerate() {
+            let script_pubkey =
+                hex::decode(&utxo.script_pub_key).map_err(|_| Error::InvalidScriptPubKey)?;
+
+            let (keyhash, script_sig) = if utxo.redeem_script.is_empty() {
+                let keyhash = address::p2wpkh_keyhash_from_script_pubkey(&script_pubkey)?;
+                (keyhash, vec![])
+            } else {
+                let redeem_script = hex::decode(&utxo.redeem_script)
+                    .map_err(|_| Error::InvalidScriptPubKey)?;
+                let keyhash = address::p2sh_p2wpkh_keyhash(&script_pubkey, &redeem_script)?;
+
+                let mut script_sig = segwit::compact_size(redeem_script.len());
+                script_sig.extend(&redeem_script);
+                (keyhash, script_sig)
+            };
+            script_sigs.push(script_sig);
+
+            let script_code = segwit::p2wpkh_script_code(&keyhash);
+
+            let digest = segwit::bip143_sighash(
+                TX_VERSION,
+                &inputs,
+                &outputs,
+                i,
+                &script_code,
+                utxo.amount as u64,
+                0,
+                SIGHASH_ALL,
+            )?;
+
+            let path = if utxo.derived_path.is_empty() {
+                None
+            } else {
+                Some(utxo.derived_path.as_str())
+            };
+            let signature =
+                self.ks
+                    .sign_recoverable_hash(&digest, self.symbol, self.address, path)?;
+
+            witnesses.push(Self::witness_stack(&digest, &signature)?);
+        }
+
+        let raw_tx = Self::serialize_segwit_tx(&inputs, &outputs, &script_sigs, &witnesses);
+        let tx_hash = Self::txid(&inputs, &outputs, &script_sigs);
+
+        Ok(BtcForkSignedTxOutput {
+            signature: hex::encode(raw_tx),
+            tx_hash: hex::encode(tx_hash),
+        })
+    }
+
+    /// Builds the `to` and (if any coins are left over) `change_address`
+    /// outputs, inserting the change output at `change_idx`.
+    fn build_outputs(tx: &BtcForkTxInput, testnet: bool) -> Result<Vec<SighashOutput>> {
+        let total: i64 = tx.unspents.iter().map(|u| u.amount).sum();
+        let change_amount = total - tx.amount - tx.fee;
+        if change_amount < 0 {
+            return Err(Error::ChangeAmountNegative.into());
+        }
+
+        let to_script = address::script_pubkey_for_address(&tx.to, testnet)?;
+        let mut outputs = vec![SighashOutput {
+            value: tx.amount as u64,
+            script_pubkey: to_script,
+        }];
+
+        if change_amount > 0 {
+            let change_script = address::script_pubkey_for_address(&tx.change_address, testnet)?;
+            let idx = (tx.change_idx as usize).min(outputs.len());
+            outputs.insert(
+                idx,
+                SighashOutput {
+                    value: change_amount as u64,
+                    script_pubkey: change_script,
+                },
+            );
+        }
+
+        Ok(outputs)
+    }
+
+    /// Recovers the signer's compressed pubkey from the 65-byte recoverable
+    /// signature and builds the `[DER signature || sighash type, pubkey]`
+    /// witness stack P2WPKH verification expects.
+    fn witness_stack(digest: &[u8; 32], signature: &[u8]) -> Result<Vec<Vec<u8>>> {
+        if signature.len() != 65 {
+            return Err(Error::InvalidSignature.into());
+        }
+
+        let recovery_id =
+            RecoveryId::from_i32(signature[64] as i32).map_err(|_| Error::InvalidSignature)?;
+        let recoverable = RecoverableSignature::from_compact(&signature[0..64], recovery_id)
+            .map_err(|_| Error::InvalidSignature)?;
+        let message = Message::from_slice(digest).map_err(|_| Error::InvalidSignature)?;
+
+        let secp = Secp256k1::verification_only();
+        let pubkey = secp
+            .recover(&message, &recoverable)
+            .map_err(|_| Error::InvalidSignature)?;
+
+        let mut der_signature = recoverable.to_standard().serialize_der().to_vec();
+        der_signature.push(SIGHASH_ALL as u8);
+
+        Ok(vec![der_signature, pubkey.serialize().to_vec()])
+    }
+
+    fn serialize_segwit_tx(
+        inputs: &[SighashInput],
+        outputs: &[SighashOutput],
+        script_sigs: &[Vec<u8>],
+        witnesses: &[Vec<Vec<u8>>],
+    ) -> Vec<u8> {
+        let mut buf = vec![];
+        buf.write_u32::<LittleEndian>(TX_VERSION).unwrap();
+        buf.push(0x00); // segwit marker
+        buf.push(0x01); // segwit flag
+        buf.extend(segwit::compact_size(inputs.len()));
+        for (input, script_sig) in inputs.iter().zip(script_sigs.iter()) {
+            buf.extend(segwit::serialize_outpoint(&input.outpoint));
+            buf.extend(segwit::compact_size(script_sig.len()));
+            buf.extend(script_sig);
+            buf.write_u32::<LittleEndian>(input.sequence).unwrap();
+        }
+        buf.extend(segwit::compact_size(outputs.len()));
+        for output in outputs.iter() {
+            buf.extend(segwit::serialize_output(output));
+        }
+        for stack in witnesses.iter() {
+            buf.extend(segwit::compact_size(stack.len()));
+            for item in stack.iter() {
+                buf.extend(segwit::compact_size(item.len()));
+                buf.extend(item);
+            }
+        }
+        buf.write_u32::<LittleEndian>(0).unwrap(); // locktime
+        buf
+    }
+
+    /// The txid is the double-SHA256 of the non-witness serialization,
+    /// reversed into the conventional display byte order.
+    fn txid(inputs: &[SighashInput], outputs: &[SighashOutput], script_sigs: &[Vec<u8>]) -> [u8; 32] {
+        let mut buf = vec![];
+        buf.write_u32::<LittleEndian>(TX_VERSION).unwrap();
+        buf.extend(segwit::compact_size(inputs.len()));
+        for (input, script_sig) in inputs.iter().zip(script_sigs.iter()) {
+            buf.extend(segwit::serialize_outpoint(&input.outpoint));
+            buf.extend(segwit::compact_size(script_sig.len()));
+            buf.extend(script_sig);
+            buf.write_u32::<LittleEndian>(input.sequence).unwrap();
+        }
+        buf.extend(segwit::compact_size(outputs.len()));
+        for output in outputs.iter() {
+            buf.extend(segwit::serialize_output(output));
+        }
+        buf.write_u32::<LittleEndian>(0).unwrap();
+
+        let mut hash = sha256d(&buf);
+        hash.reverse();
+        hash
+    }
+}
+
+impl TransactionSigner<BtcForkTxInput, BtcForkSignedTxOutput> for Keystore {
+    fn sign_transaction(
+        &mut self,
+        symbol: &str,
+        address: &str,
+        tx: &BtcForkTxInput,
+    ) -> Result<BtcForkSignedTxOutput> {
+        let mut signer = BtcForkTxSigner {
+            ks: self,
+            symbol,
+            address,
+        };
+
+        signer.sign_segwit_transaction(tx)
+    }
+}
@@ -14,6 +14,11 @@ pub struct Utxo {
     pub derived_path: std::string::String,
     #[prost(int64, tag="7")]
     pub sequence: i64,
+    /// Hex-encoded redeem script, required for P2SH-P2WPKH unspents (their
+    /// `script_pub_key` hashes this rather than embedding the witness
+    /// program directly); left empty for native P2WPKH.
+    #[prost(string, tag="8")]
+    pub redeem_script: std::string::String,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct BtcForkTxInput {
@@ -0,0 +1,193 @@
+use crate::hash::blake2b_160;
+use crate::transaction::Script;
+use crate::Error;
+use bech32::{FromBase32, ToBase32, Variant};
+use tcx_chain::{Address, Result};
+
+const MAINNET_HRP: &str = "ckb";
+const TESTNET_HRP: &str = "ckt";
+
+// legacy (pre-CKB2021) short address payload: format(1) | code_hash_index(1) | args
+const SHORT_FORMAT_TYPE: u8 = 0x01;
+const SHORT_CODE_HASH_INDEX_SIGHASH_ALL: u8 = 0x00;
+
+// CKB2021 full address payload: format(1) | code_hash(32) | hash_type(1) | args
+const FULL_FORMAT_TYPE: u8 = 0x00;
+const FULL_PAYLOAD_HEADER_LEN: usize = 1 + 32 + 1;
+
+pub struct CkbAddress();
+
+impl CkbAddress {
+    fn hrp(coin: Option<&str>) -> &'static str {
+        match coin {
+            Some("TESTNET") => TESTNET_HRP,
+            _ => MAINNET_HRP,
+        }
+    }
+
+    /// Renders a CKB2021 "full" address for `script`: the payload is
+    /// regrouped from 8-bit to 5-bit words and bech32m-encoded, as adopted
+    /// in the CKB2021 hard fork.
+    pub fn encode_full(script: &Script, coin: Option<&str>) -> Result<String> {
+        let hash_type = Script::hash_type_to_byte(&script.hash_type)?;
+
+        let mut payload = vec![FULL_FORMAT_TYPE];
+        payload.extend_from_slice(&script.code_hash);
+        payload.push(hash_type);
+        payload.extend_from_slice(&script.args);
+
+        bech32::encode(Self::hrp(coin), payload.to_base32(), Variant::Bech32m)
+            .map_err(|_| Error::InvalidAddress.into())
+    }
+
+    /// Parses a CKB2021 full address back into its `Script`. Rejects
+    /// addresses that checksum as plain bech32 (the pre-fork variant),
+    /// have an unknown hrp, or carry a format byte other than "full".
+    pub fn decode_full(address: &str) -> Result<Script> {
+        let (hrp, data, variant) =
+            bech32::decode(address).map_err(|_| Error::InvalidAddress)?;
+
+        if variant != Variant::Bech32m {
+            return Err(Error::InvalidAddress.into());
+        }
+
+        if hrp != MAINNET_HRP && hrp != TESTNET_HRP {
+            return Err(Error::InvalidAddress.into());
+        }
+
+        let payload = Vec::<u8>::from_base32(&data).map_err(|_| Error::InvalidAddress)?;
+
+        if payload.len() < FULL_PAYLOAD_HEADER_LEN || payload[0] != FULL_FORMAT_TYPE {
+            return Err(Error::InvalidAddress.into());
+        }
+
+        let code_hash = payload[1..33].to_vec();
+        let hash_type = Script::hash_type_from_byte(payload[33])?;
+        let args = payload[FULL_PAYLOAD_HEADER_LEN..].to_vec();
+
+        Ok(Script {
+            args,
+            code_hash,
+            hash_type,
+        })
+    }
+}
+
+impl Address for CkbAddress {
+    fn from_public_key(public_key: &[u8], coin: Option<&str>) -> Result<String> {
+        let hash = blake2b_160(public_key);
+
+        let mut payload = vec![SHORT_FORMAT_TYPE, SHORT_CODE_HASH_INDEX_SIGHASH_ALL];
+        payload.extend_from_slice(&hash);
+
+        bech32::encode(Self::hrp(coin), payload.to_base32(), Variant::Bech32)
+            .map_err(|_| Error::InvalidAddress.into())
+    }
+
+    fn is_valid(address: &str) -> bool {
+        if let Ok((hrp, _, variant)) = bech32::decode(address) {
+            (hrp == MAINNET_HRP || hrp == TESTNET_HRP)
+                && (variant == Variant::Bech32 || variant == Variant::Bech32m)
+        } else {
+            false
+        }
+    }
+}
+
+impl CkbAddress {
+    /// Normalizes a CKB2021 full (bech32m) address down to the legacy short
+    /// address form `Keystore` accounts are derived in, so `CkbTxSigner` can
+    /// be handed either form as the signing `address`. Addresses that aren't
+    /// full addresses (including already-short ones) are returned unchanged.
+    pub fn normalize(address: &str) -> Result<String> {
+        let script = match Self::decode_full(address) {
+            Ok(script) => script,
+            Err(_) => return Ok(address.to_string()),
+        };
+
+        let (hrp, _, _) = bech32::decode(address).map_err(|_| Error::InvalidAddress)?;
+
+        let mut payload = vec![SHORT_FORMAT_TYPE, SHORT_CODE_HASH_INDEX_SIGHASH_ALL];
+        payload.extend_from_slice(&script.args);
+
+        bech32::encode(&hrp, payload.to_base32(), Variant::Bech32)
+            .map_err(|_| Error::InvalidAddress.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CkbAddress;
+    use crate::transaction::Script;
+    use bech32::FromBase32;
+
+    #[test]
+    fn encode_decode_full_address_roundtrip() {
+        let script = Script {
+            args: hex::decode("ab201f55e6f3b3dc1b4b166b071361e2de5c9fc1").unwrap(),
+            code_hash: hex::decode(
+                "9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8",
+            )
+            .unwrap(),
+            hash_type: "type".to_string(),
+        };
+
+        let address = CkbAddress::encode_full(&script, Some("TESTNET")).unwrap();
+        assert!(address.starts_with("ckt1"));
+
+        let decoded = CkbAddress::decode_full(&address).unwrap();
+        assert_eq!(decoded.args, script.args);
+        assert_eq!(decoded.code_hash, script.code_hash);
+        assert_eq!(decoded.hash_type, script.hash_type);
+    }
+
+    #[test]
+    fn normalize_converts_full_address_to_matching_short_address() {
+        let pubkey_hash = hex::decode("ab201f55e6f3b3dc1b4b166b071361e2de5c9fc1").unwrap();
+        let script = Script {
+            args: pubkey_hash.clone(),
+            code_hash: hex::decode(
+                "9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8",
+            )
+            .unwrap(),
+            hash_type: "type".to_string(),
+        };
+
+        let full = CkbAddress::encode_full(&script, Some("TESTNET")).unwrap();
+        let short = CkbAddress::normalize(&full).unwrap();
+
+        assert!(CkbAddress::is_valid(&short));
+        let (_, data, variant) = bech32::decode(&short).unwrap();
+        assert_eq!(variant, bech32::Variant::Bech32);
+        let payload = Vec::<u8>::from_base32(&data).unwrap();
+        assert_eq!(payload[2..], pubkey_hash[..]);
+    }
+
+    #[test]
+    fn normalize_leaves_short_address_unchanged() {
+        let short = "ckt1qyqvsv5240xeefr8eywupw5n0nnur6acv8xcmfhss5";
+        assert_eq!(CkbAddress::normalize(short).unwrap(), short);
+    }
+
+    #[test]
+    fn decode_full_rejects_legacy_bech32_checksum() {
+        let script = Script {
+            args: hex::decode("ab201f55e6f3b3dc1b4b166b071361e2de5c9fc1").unwrap(),
+            code_hash: hex::decode(
+                "9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8",
+            )
+            .unwrap(),
+            hash_type: "type".to_string(),
+        };
+
+        // a plain bech32 (non-m) encoding of the same payload must not
+        // be accepted as a CKB2021 full address.
+        let mut payload = vec![0x00u8];
+        payload.extend_from_slice(&script.code_hash);
+        payload.push(1);
+        payload.extend_from_slice(&script.args);
+        let legacy = bech32::encode("ckt", bech32::ToBase32::to_base32(&payload), bech32::Variant::Bech32).unwrap();
+
+        assert!(CkbAddress::decode_full(&legacy).is_err());
+    }
+}
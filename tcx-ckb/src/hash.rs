@@ -0,0 +1,25 @@
+use blake2b_rs::{Blake2b, Blake2bBuilder};
+
+const CKB_HASH_PERSONALIZATION: &[u8] = b"ckb-default-hash";
+
+pub fn new_blake2b() -> Blake2b {
+    Blake2bBuilder::new(32)
+        .personal(CKB_HASH_PERSONALIZATION)
+        .build()
+}
+
+pub fn blake2b_256(data: impl AsRef<[u8]>) -> [u8; 32] {
+    let mut result = [0u8; 32];
+    let mut blake2b = new_blake2b();
+    blake2b.update(data.as_ref());
+    blake2b.finalize(&mut result);
+    result
+}
+
+/// CKB's "blake160": the first 20 bytes of the blake2b-256 hash, used to
+/// compress public keys into lock args.
+pub fn blake2b_160(data: impl AsRef<[u8]>) -> [u8; 20] {
+    let mut result = [0u8; 20];
+    result.copy_from_slice(&blake2b_256(data)[0..20]);
+    result
+}
@@ -0,0 +1,44 @@
+pub mod address;
+pub mod hash;
+pub mod serializer;
+pub mod signer;
+pub mod transaction;
+pub mod vanity;
+
+use failure::Fail;
+
+pub type Result<T> = std::result::Result<T, failure::Error>;
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum Error {
+    #[fail(display = "invalid_tx_hash")]
+    InvalidTxHash,
+    #[fail(display = "witness_empty")]
+    WitnessEmpty,
+    #[fail(display = "witness_group_empty")]
+    WitnessGroupEmpty,
+    #[fail(display = "required_witness")]
+    RequiredWitness,
+    #[fail(display = "cell_input_not_cached")]
+    CellInputNotCached,
+    #[fail(display = "invalid_output_point")]
+    InvalidOutputPoint,
+    #[fail(display = "invalid_address")]
+    InvalidAddress,
+    #[fail(display = "invalid_script_hash_type")]
+    InvalidScriptHashType,
+    #[fail(display = "invalid_multisig_signature")]
+    InvalidMultisigSignature,
+    #[fail(display = "multisig_pubkey_not_in_config")]
+    MultisigPubkeyNotInConfig,
+    #[fail(display = "partial_signed_tx_hash_mismatch")]
+    PartialSignedTxHashMismatch,
+    #[fail(display = "partial_signature_incomplete")]
+    PartialSignatureIncomplete,
+    #[fail(display = "invalid_molecule_encoding")]
+    InvalidMoleculeEncoding,
+    #[fail(display = "multisig_config_missing")]
+    MultisigConfigMissing,
+    #[fail(display = "vanity_pattern_not_found")]
+    VanityPatternNotFound,
+}
@@ -2,6 +2,8 @@ use byteorder::{ByteOrder, LittleEndian};
 use std::collections::HashMap;
 use tcx_chain::Result;
 
+use crate::Error;
+
 pub struct Serializer();
 
 impl Serializer {
@@ -81,6 +83,117 @@ impl Serializer {
 
         ret
     }
+
+    /// A molecule `table`: fields may be heterogeneous and individually
+    /// known types, but it's laid out exactly like `dynamic_vec` - full
+    /// size header, then one offset per field, then the field bodies back
+    /// to back (the offset/size header is emitted even for fields that are
+    /// themselves fixed-size).
+    pub fn serialize_table(fields: &Vec<Vec<u8>>) -> Vec<u8> {
+        Serializer::serialize_dynamic_vec(fields)
+    }
+
+    /// A molecule `option`: empty bytes for `None`, the inner encoding for
+    /// `Some`.
+    pub fn serialize_option(value: &Option<Vec<u8>>) -> Vec<u8> {
+        match value {
+            Some(inner) => inner.clone(),
+            None => vec![],
+        }
+    }
+
+    /// A molecule `union`: a 4-byte little-endian item-id tag followed by
+    /// the selected variant's encoding.
+    pub fn serialize_union(item_id: u32, value: &Vec<u8>) -> Vec<u8> {
+        let mut ret = Serializer::serialize_u32(item_id);
+        ret.extend(value);
+        ret
+    }
+
+    pub fn deserialize_table(bytes: &[u8]) -> Result<Vec<Vec<u8>>> {
+        Serializer::deserialize_dynamic_layout(bytes)
+    }
+
+    pub fn deserialize_dynamic_vec(bytes: &[u8]) -> Result<Vec<Vec<u8>>> {
+        Serializer::deserialize_dynamic_layout(bytes)
+    }
+
+    /// Shared by `table`/`dynamic_vec`: validates the total-size header and
+    /// that per-field offsets are monotonically increasing and in-bounds
+    /// before slicing out each field.
+    fn deserialize_dynamic_layout(bytes: &[u8]) -> Result<Vec<Vec<u8>>> {
+        if bytes.len() < 4 {
+            return Err(Error::InvalidMoleculeEncoding.into());
+        }
+
+        let total_size = LittleEndian::read_u32(&bytes[0..4]) as usize;
+        if total_size != bytes.len() {
+            return Err(Error::InvalidMoleculeEncoding.into());
+        }
+
+        if total_size == 4 {
+            return Ok(vec![]);
+        }
+
+        if total_size < 8 {
+            return Err(Error::InvalidMoleculeEncoding.into());
+        }
+
+        let header_length = LittleEndian::read_u32(&bytes[4..8]) as usize;
+        if header_length < 8 || (header_length - 4) % 4 != 0 || header_length > total_size {
+            return Err(Error::InvalidMoleculeEncoding.into());
+        }
+
+        let field_count = (header_length - 4) / 4;
+        let mut offsets = Vec::with_capacity(field_count);
+
+        for i in 0..field_count {
+            let start = 4 + i * 4;
+            if start + 4 > bytes.len() {
+                return Err(Error::InvalidMoleculeEncoding.into());
+            }
+            offsets.push(LittleEndian::read_u32(&bytes[start..start + 4]) as usize);
+        }
+
+        if offsets[0] != header_length {
+            return Err(Error::InvalidMoleculeEncoding.into());
+        }
+
+        let mut fields = Vec::with_capacity(field_count);
+        for i in 0..field_count {
+            let start = offsets[i];
+            let end = if i + 1 < field_count {
+                offsets[i + 1]
+            } else {
+                total_size
+            };
+
+            if start > end || end > total_size {
+                return Err(Error::InvalidMoleculeEncoding.into());
+            }
+
+            fields.push(bytes[start..end].to_vec());
+        }
+
+        Ok(fields)
+    }
+
+    pub fn deserialize_option(bytes: &[u8]) -> Option<Vec<u8>> {
+        if bytes.is_empty() {
+            None
+        } else {
+            Some(bytes.to_vec())
+        }
+    }
+
+    pub fn deserialize_union(bytes: &[u8]) -> Result<(u32, Vec<u8>)> {
+        if bytes.len() < 4 {
+            return Err(Error::InvalidMoleculeEncoding.into());
+        }
+
+        let item_id = LittleEndian::read_u32(&bytes[0..4]);
+        Ok((item_id, bytes[4..].to_vec()))
+    }
 }
 
 #[cfg(test)]
@@ -117,4 +230,59 @@ mod tests {
         ]);
         assert_eq!(hex::encode(bytes), "34000000180000001e00000022000000280000002d00000002000000123400000000020000000567010000008903000000abcdef");
     }
+
+    #[test]
+    fn serialize_table_roundtrip() {
+        let fields = vec![
+            hex::decode("0011").unwrap(),
+            hex::decode("").unwrap(),
+            hex::decode("abcdef").unwrap(),
+        ];
+
+        let bytes = Serializer::serialize_table(&fields);
+        // same layout as dynamic_vec
+        assert_eq!(bytes, Serializer::serialize_dynamic_vec(&fields));
+
+        let decoded = Serializer::deserialize_table(&bytes).unwrap();
+        assert_eq!(decoded, fields);
+    }
+
+    #[test]
+    fn serialize_option_some_and_none() {
+        assert_eq!(Serializer::serialize_option(&None), Vec::<u8>::new());
+        assert_eq!(
+            Serializer::deserialize_option(&Serializer::serialize_option(&None)),
+            None
+        );
+
+        let some = Some(hex::decode("1234").unwrap());
+        let bytes = Serializer::serialize_option(&some);
+        assert_eq!(hex::encode(&bytes), "1234");
+        assert_eq!(Serializer::deserialize_option(&bytes), some);
+    }
+
+    #[test]
+    fn serialize_union_roundtrip() {
+        let bytes = Serializer::serialize_union(7, &hex::decode("abcd").unwrap());
+        assert_eq!(hex::encode(&bytes), "07000000abcd");
+
+        let (item_id, value) = Serializer::deserialize_union(&bytes).unwrap();
+        assert_eq!(item_id, 7);
+        assert_eq!(hex::encode(value), "abcd");
+
+        assert!(Serializer::deserialize_union(&[0x00, 0x00]).is_err());
+    }
+
+    #[test]
+    fn deserialize_dynamic_vec_rejects_malformed_input() {
+        // total-size header lies about the buffer length
+        assert!(Serializer::deserialize_dynamic_vec(&hex::decode("ff0000000102").unwrap()).is_err());
+
+        // offsets are not monotonically increasing: field0 starts at 12,
+        // but field1 (and so field0's end) is claimed to start at 5.
+        assert!(Serializer::deserialize_dynamic_vec(
+            &hex::decode("100000000c0000000500000000000000").unwrap()
+        )
+        .is_err());
+    }
 }
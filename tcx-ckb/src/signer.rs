@@ -1,10 +1,16 @@
 use failure::Fail;
 use tcx_chain::{Keystore, Result, TransactionSigner};
 
-use crate::hash::new_blake2b;
+use crate::address::CkbAddress;
+use crate::hash::{blake2b_160, new_blake2b};
 use crate::serializer::Serializer;
-use crate::transaction::{CachedCell, CellInput, OutPoint, TxInput, TxOutput, Witness};
+use crate::transaction::{
+    CachedCell, CellInput, MultisigConfig, OutPoint, PartialSignedTxOutput, PartialWitness,
+    SignatureSlot, TxInput, TxOutput, Witness,
+};
 use crate::Error;
+use secp256k1::recovery::{RecoverableSignature, RecoveryId};
+use secp256k1::{Message, Secp256k1};
 use std::collections::HashMap;
 use tcx_chain::ChainSigner;
 
@@ -44,9 +50,9 @@ impl<'a> CkbTxSigner<'a> {
                 ws.extend(&witnesses[input_cells.len()..]);
             }
 
-            let path = &input_cells[item.1[0]].derive_path;
+            let cell = &input_cells[item.1[0]];
 
-            let signed_witness = self.sign_witness_group(tx_hash, &ws, path)?;
+            let signed_witness = self.sign_witness_group(tx_hash, &ws, cell)?;
             raw_witnesses[item.1[0]] = signed_witness;
         }
 
@@ -57,8 +63,22 @@ impl<'a> CkbTxSigner<'a> {
         &mut self,
         tx_hash: &[u8],
         witness_group: &Vec<&Witness>,
-        path: &str,
+        cell: &CachedCell,
     ) -> Result<Witness> {
+        let (witness, _) = self.sign_witness_group_with_index(tx_hash, witness_group, cell)?;
+        Ok(witness)
+    }
+
+    /// Like `sign_witness_group`, but also returns the `(pubkey_hashes` index,
+    /// 65-byte signature)` this call filled for a multisig cell, so callers
+    /// building `PartialWitness` bookkeeping (`sign_witnesses_partial`) don't
+    /// have to re-derive it from the packed lock bytes.
+    fn sign_witness_group_with_index(
+        &mut self,
+        tx_hash: &[u8],
+        witness_group: &Vec<&Witness>,
+        cell: &CachedCell,
+    ) -> Result<(Witness, Option<(usize, Vec<u8>)>)> {
         if witness_group.len() == 0 {
             return Err(Error::WitnessGroupEmpty.into());
         }
@@ -66,7 +86,14 @@ impl<'a> CkbTxSigner<'a> {
         let first = &witness_group[0];
 
         let mut empty_witness = Witness {
-            lock: SIGNATURE_PLACEHOLDER.to_vec(),
+            lock: match &cell.multisig_config {
+                Some(config) => {
+                    let mut lock = config.serialize();
+                    lock.extend(vec![0u8; 65 * config.threshold as usize]);
+                    lock
+                }
+                None => SIGNATURE_PLACEHOLDER.to_vec(),
+            },
             input_type: first.input_type.clone(),
             output_type: first.output_type.clone(),
         };
@@ -88,13 +115,66 @@ impl<'a> CkbTxSigner<'a> {
         let mut result = [0u8; 32];
         s.finalize(&mut result);
 
-        let opt_path = if path.len() > 0 { Some(path) } else { None };
+        let path = &cell.derive_path;
+        let opt_path = if path.len() > 0 {
+            Some(path.as_str())
+        } else {
+            None
+        };
 
-        empty_witness.lock =
+        let signature =
             self.ks
                 .sign_recoverable_hash(&result, self.symbol, self.address, opt_path)?;
 
-        Ok(empty_witness)
+        let multisig_match = match &cell.multisig_config {
+            Some(config) => {
+                let index = Self::find_signature_index(config, &result, &signature)?;
+                empty_witness.lock = config.pack_lock(&[(index, signature.clone())]);
+                Some((index, signature))
+            }
+            None => {
+                empty_witness.lock = signature;
+                None
+            }
+        };
+
+        Ok((empty_witness, multisig_match))
+    }
+
+    /// Recovers the signer's pubkey hash from `signature` and locates its
+    /// index in `config.pubkey_hashes`.
+    fn find_signature_index(
+        config: &MultisigConfig,
+        digest: &[u8; 32],
+        signature: &[u8],
+    ) -> Result<usize> {
+        let pubkey_hash = Self::recover_pubkey_hash(digest, signature)?;
+
+        config
+            .pubkey_hashes
+            .iter()
+            .position(|hash| hash.as_slice() == pubkey_hash)
+            .ok_or_else(|| Error::MultisigPubkeyNotInConfig.into())
+    }
+
+    fn recover_pubkey_hash(digest: &[u8; 32], signature: &[u8]) -> Result<[u8; 20]> {
+        if signature.len() != 65 {
+            return Err(Error::InvalidMultisigSignature.into());
+        }
+
+        let recovery_id = RecoveryId::from_i32(signature[64] as i32)
+            .map_err(|_| Error::InvalidMultisigSignature)?;
+        let recoverable_sig = RecoverableSignature::from_compact(&signature[0..64], recovery_id)
+            .map_err(|_| Error::InvalidMultisigSignature)?;
+        let message =
+            Message::from_slice(digest).map_err(|_| Error::InvalidMultisigSignature)?;
+
+        let secp = Secp256k1::verification_only();
+        let pubkey = secp
+            .recover(&message, &recoverable_sig)
+            .map_err(|_| Error::InvalidMultisigSignature)?;
+
+        Ok(blake2b_160(&pubkey.serialize()))
     }
 
     fn group_script(
@@ -120,41 +200,153 @@ impl<'a> CkbTxSigner<'a> {
 
         Ok(map)
     }
-}
 
-impl TransactionSigner<TxInput, TxOutput> for Keystore {
-    fn sign_transaction(&mut self, symbol: &str, address: &str, tx: &TxInput) -> Result<TxOutput> {
-        if tx.witnesses.len() == 0 {
-            return Err(Error::RequiredWitness.into());
+    /// Like `sign_witnesses`, but also records per-multisig-group signature
+    /// slot bookkeeping so the result can be combined with other partially
+    /// signed results instead of being the final witnesses.
+    pub fn sign_witnesses_partial(
+        &mut self,
+        tx_hash: &[u8],
+        witnesses: &Vec<Witness>,
+        input_cells: &Vec<&CachedCell>,
+    ) -> Result<(Vec<Witness>, Vec<PartialWitness>)> {
+        if tx_hash.len() != 32 {
+            return Err(Error::InvalidTxHash.into());
         }
 
-        let find_cache_cell = |x: &OutPoint| -> Result<&CachedCell> {
-            for y in tx.cached_cells.iter() {
-                if y.out_point.is_some() {
-                    let point = y.out_point.as_ref().unwrap();
-                    if point.index == x.index && point.tx_hash == x.tx_hash {
-                        return Ok(y);
+        if witnesses.len() == 0 {
+            return Err(Error::WitnessEmpty.into());
+        }
+
+        let grouped_scripts = self.group_script(input_cells)?;
+
+        let mut raw_witnesses = witnesses.to_vec();
+        let mut partial_witnesses = vec![];
+
+        for item in grouped_scripts.iter() {
+            let mut ws = vec![];
+            ws.extend(item.1.iter().map(|i| &witnesses[*i]));
+
+            if witnesses.len() > input_cells.len() {
+                ws.extend(&witnesses[input_cells.len()..]);
+            }
+
+            let cell = &input_cells[item.1[0]];
+            let witness_index = item.1[0];
+
+            match self.sign_witness_group_with_index(tx_hash, &ws, cell) {
+                Ok((signed_witness, multisig_match)) => {
+                    raw_witnesses[witness_index] = signed_witness.clone();
+
+                    if let Some(config) = &cell.multisig_config {
+                        let filled_index = multisig_match.as_ref().map(|(index, _)| *index);
+
+                        let slots = config
+                            .pubkey_hashes
+                            .iter()
+                            .enumerate()
+                            .map(|(i, pubkey_hash)| {
+                                let filled = Some(i) == filled_index;
+                                SignatureSlot {
+                                    pubkey_hash: pubkey_hash.clone(),
+                                    derive_path: if filled {
+                                        cell.derive_path.clone()
+                                    } else {
+                                        "".to_string()
+                                    },
+                                    filled,
+                                    signature: if filled {
+                                        multisig_match.as_ref().unwrap().1.clone()
+                                    } else {
+                                        vec![]
+                                    },
+                                }
+                            })
+                            .collect();
+
+                        partial_witnesses.push(PartialWitness {
+                            witness_index: witness_index as u32,
+                            lock: signed_witness.lock,
+                            slots,
+                            multisig_config: Some(config.clone()),
+                        });
                     }
                 }
+                // this keystore isn't a participant of this multisig group
+                // (or otherwise can't produce a signature for it): leave
+                // every slot empty so another device's result can still
+                // fill them in, instead of aborting the whole transaction.
+                // A non-multisig cell has no co-signer to fall back on, so
+                // its signing error still propagates.
+                Err(err) => match &cell.multisig_config {
+                    Some(config) => {
+                        let slots = config
+                            .pubkey_hashes
+                            .iter()
+                            .map(|pubkey_hash| SignatureSlot {
+                                pubkey_hash: pubkey_hash.clone(),
+                                derive_path: "".to_string(),
+                                filled: false,
+                                signature: vec![],
+                            })
+                            .collect();
+
+                        partial_witnesses.push(PartialWitness {
+                            witness_index: witness_index as u32,
+                            lock: config.pack_lock(&[]),
+                            slots,
+                            multisig_config: Some(config.clone()),
+                        });
+                    }
+                    None => return Err(err),
+                },
             }
+        }
 
-            Err(Error::CellInputNotCached.into())
-        };
-
-        let mut input_cells: Vec<&CachedCell> = vec![];
+        Ok((raw_witnesses, partial_witnesses))
+    }
+}
 
-        for x in tx.inputs.iter() {
-            if x.previous_output.is_none() {
-                return Err(Error::InvalidOutputPoint.into());
+fn resolve_input_cells<'a>(tx: &'a TxInput) -> Result<Vec<&'a CachedCell>> {
+    let find_cache_cell = |x: &OutPoint| -> Result<&'a CachedCell> {
+        for y in tx.cached_cells.iter() {
+            if y.out_point.is_some() {
+                let point = y.out_point.as_ref().unwrap();
+                if point.index == x.index && point.tx_hash == x.tx_hash {
+                    return Ok(y);
+                }
             }
+        }
+
+        Err(Error::CellInputNotCached.into())
+    };
+
+    let mut input_cells: Vec<&CachedCell> = vec![];
+
+    for x in tx.inputs.iter() {
+        if x.previous_output.is_none() {
+            return Err(Error::InvalidOutputPoint.into());
+        }
+
+        input_cells.push(find_cache_cell(x.previous_output.as_ref().unwrap())?);
+    }
 
-            input_cells.push(find_cache_cell(x.previous_output.as_ref().unwrap())?);
+    Ok(input_cells)
+}
+
+impl TransactionSigner<TxInput, TxOutput> for Keystore {
+    fn sign_transaction(&mut self, symbol: &str, address: &str, tx: &TxInput) -> Result<TxOutput> {
+        if tx.witnesses.len() == 0 {
+            return Err(Error::RequiredWitness.into());
         }
 
+        let input_cells = resolve_input_cells(tx)?;
+        let address = CkbAddress::normalize(address)?;
+
         let mut signer = CkbTxSigner {
             ks: self,
             symbol,
-            address,
+            address: &address,
         };
 
         let signed_witnesses = signer.sign_witnesses(&tx.tx_hash, &tx.witnesses, &input_cells)?;
@@ -168,10 +360,67 @@ impl TransactionSigner<TxInput, TxOutput> for Keystore {
     }
 }
 
+impl TransactionSigner<TxInput, PartialSignedTxOutput> for Keystore {
+    /// Signs every witness group this keystore can, leaving any multisig
+    /// slots it cannot fill empty. The result is meant to be passed to
+    /// `Keystore::merge` together with other devices' results, then
+    /// `finalize`d once complete.
+    fn sign_transaction(
+        &mut self,
+        symbol: &str,
+        address: &str,
+        tx: &TxInput,
+    ) -> Result<PartialSignedTxOutput> {
+        if tx.witnesses.len() == 0 {
+            return Err(Error::RequiredWitness.into());
+        }
+
+        let input_cells = resolve_input_cells(tx)?;
+        let address = CkbAddress::normalize(address)?;
+
+        let mut signer = CkbTxSigner {
+            ks: self,
+            symbol,
+            address: &address,
+        };
+
+        let (signed_witnesses, partial_witnesses) =
+            signer.sign_witnesses_partial(&tx.tx_hash, &tx.witnesses, &input_cells)?;
+
+        Ok(PartialSignedTxOutput {
+            tx_hash: tx.tx_hash.clone(),
+            witnesses: signed_witnesses,
+            partial_witnesses,
+        })
+    }
+}
+
+/// Entry point for combining the partial signatures two devices produced
+/// for the same CKB transaction. The actual merging lives on
+/// `PartialSignedTxOutput::combine`; this trait just gives callers that
+/// already hold a `Keystore` a single place to reach it from, the same way
+/// `sign_transaction` is reached through `TransactionSigner`.
+pub trait KeystoreMerge<T> {
+    fn merge(&self, a: &T, b: &T) -> Result<T>;
+}
+
+impl KeystoreMerge<PartialSignedTxOutput> for Keystore {
+    fn merge(
+        &self,
+        a: &PartialSignedTxOutput,
+        b: &PartialSignedTxOutput,
+    ) -> Result<PartialSignedTxOutput> {
+        a.combine(b)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::address::CkbAddress;
-    use crate::transaction::{CachedCell, CellInput, OutPoint, Script, TxInput, Witness};
+    use crate::signer::KeystoreMerge;
+    use crate::transaction::{
+        CachedCell, CellInput, OutPoint, PartialSignedTxOutput, Script, TxInput, Witness,
+    };
     use tcx_chain::{Keystore, TransactionSigner};
     use tcx_constants::{CoinInfo, CurveType};
 
@@ -207,6 +456,7 @@ mod tests {
             output_data_len: 0,
             status: "".to_string(),
             data_hash: vec![],
+            multisig_config: None,
         };
 
         let cached_cells = vec![
@@ -379,4 +629,202 @@ mod tests {
 
         assert_eq!(hex::encode(tx_output.witnesses[1].serialize()), "550000001000000055000000550000004100000091af5eeb1632565dc4a9fb1c6e08d1f1c7da96e10ee00595a2db208f1d15faca03332a1f0f7a0f8522f6e112bb8dde4ed0015d1683b998744a0d8644f0dfd0f800");
     }
+
+    #[test]
+    fn sign_multisig_witness_group() {
+        use crate::transaction::MultisigConfig;
+        use bech32::FromBase32;
+
+        let tx_hash =
+            hex::decode("4a4bcfef1b7448e27edf533df2f1de9f56be05eba645fb83f42d55816797ad2a")
+                .unwrap();
+        let empty_witness = Witness {
+            lock: vec![],
+            input_type: vec![],
+            output_type: vec![],
+        };
+
+        let mut ks = Keystore::from_private_key(
+            "dcec27d0d975b0378471183a03f7071dea8532aaf968be796719ecd20af6988f",
+            "Password",
+        );
+        ks.unlock_by_password("Password");
+
+        let coin_info = CoinInfo {
+            coin: "CKB".to_string(),
+            derivation_path: "".to_string(),
+            curve: CurveType::SECP256k1,
+            network: "TESTNET".to_string(),
+            seg_wit: "".to_string(),
+        };
+
+        let account = ks.derive_coin::<CkbAddress>(&coin_info).unwrap().clone();
+
+        // the short address payload is `format(1) | code_hash_index(1) | blake160(pubkey)`.
+        let (_, data, _) = bech32::decode(&account.address).unwrap();
+        let payload = Vec::<u8>::from_base32(&data).unwrap();
+        let pubkey_hash = payload[2..].to_vec();
+
+        let config = MultisigConfig {
+            require_first_n: 0,
+            threshold: 1,
+            pubkey_hashes: vec![vec![0u8; 20], pubkey_hash.clone()],
+        };
+
+        let cached_cell = CachedCell {
+            derive_path: "".to_string(),
+            r#type: None,
+            block_hash: vec![],
+            capacity: 0,
+            lock: Some(Script {
+                args: vec![],
+                code_hash: vec![],
+                hash_type: "type".to_string(),
+            }),
+            out_point: Some(OutPoint {
+                tx_hash: hex::decode(
+                    "e3c3c5b5bd600803286c14acf09f47947735b25e5f5b5b546548c9ceca202cf9",
+                )
+                .unwrap(),
+                index: 0,
+            }),
+            cellbase: false,
+            output_data_len: 0,
+            status: "".to_string(),
+            data_hash: vec![],
+            multisig_config: Some(config.clone()),
+        };
+
+        let tx_input = TxInput {
+            inputs: vec![CellInput {
+                previous_output: cached_cell.out_point.clone(),
+                since: "".to_string(),
+            }],
+            witnesses: vec![empty_witness.clone()],
+            tx_hash,
+            cached_cells: vec![cached_cell],
+        };
+
+        let tx_output = ks
+            .sign_transaction("CKB", &account.address, &tx_input)
+            .unwrap();
+
+        let lock = &tx_output.witnesses[0].lock;
+        let script_len = config.serialize().len();
+
+        // threshold is 1, so the lock only ever has a single physical
+        // signature slot, regardless of our key being at pubkey_hashes[1].
+        assert_eq!(lock.len(), script_len + 65);
+        assert_ne!(&lock[script_len..script_len + 65], &[0u8; 65][..]);
+    }
+
+    #[test]
+    fn combine_and_finalize_partial_signatures() {
+        use crate::transaction::MultisigConfig;
+        use bech32::FromBase32;
+
+        fn pubkey_hash(ks: &mut Keystore, coin_info: &CoinInfo) -> (String, Vec<u8>) {
+            let account = ks.derive_coin::<CkbAddress>(coin_info).unwrap().clone();
+            let (_, data, _) = bech32::decode(&account.address).unwrap();
+            let payload = Vec::<u8>::from_base32(&data).unwrap();
+            (account.address, payload[2..].to_vec())
+        }
+
+        let tx_hash =
+            hex::decode("4a4bcfef1b7448e27edf533df2f1de9f56be05eba645fb83f42d55816797ad2a")
+                .unwrap();
+        let empty_witness = Witness {
+            lock: vec![],
+            input_type: vec![],
+            output_type: vec![],
+        };
+
+        let coin_info = CoinInfo {
+            coin: "CKB".to_string(),
+            derivation_path: "".to_string(),
+            curve: CurveType::SECP256k1,
+            network: "TESTNET".to_string(),
+            seg_wit: "".to_string(),
+        };
+
+        let mut ks_a = Keystore::from_private_key(
+            "dcec27d0d975b0378471183a03f7071dea8532aaf968be796719ecd20af6988f",
+            "Password",
+        );
+        ks_a.unlock_by_password("Password");
+        let (address_a, hash_a) = pubkey_hash(&mut ks_a, &coin_info);
+
+        let mut ks_b = Keystore::from_private_key(
+            "eeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeee",
+            "Password",
+        );
+        ks_b.unlock_by_password("Password");
+        let (address_b, hash_b) = pubkey_hash(&mut ks_b, &coin_info);
+
+        // N=3, threshold=2, with a non-participating key at index 1 between
+        // the two signers: the slot a signer's signature lands in must be
+        // tracked separately from its (possibly >= threshold) raw index.
+        let config = MultisigConfig {
+            require_first_n: 0,
+            threshold: 2,
+            pubkey_hashes: vec![hash_a, vec![0u8; 20], hash_b],
+        };
+
+        let cached_cell = CachedCell {
+            derive_path: "".to_string(),
+            r#type: None,
+            block_hash: vec![],
+            capacity: 0,
+            lock: Some(Script {
+                args: vec![],
+                code_hash: vec![],
+                hash_type: "type".to_string(),
+            }),
+            out_point: Some(OutPoint {
+                tx_hash: hex::decode(
+                    "e3c3c5b5bd600803286c14acf09f47947735b25e5f5b5b546548c9ceca202cf9",
+                )
+                .unwrap(),
+                index: 0,
+            }),
+            cellbase: false,
+            output_data_len: 0,
+            status: "".to_string(),
+            data_hash: vec![],
+            multisig_config: Some(config),
+        };
+
+        let tx_input = TxInput {
+            inputs: vec![CellInput {
+                previous_output: cached_cell.out_point.clone(),
+                since: "".to_string(),
+            }],
+            witnesses: vec![empty_witness],
+            tx_hash,
+            cached_cells: vec![cached_cell],
+        };
+
+        let partial_a = <Keystore as TransactionSigner<TxInput, PartialSignedTxOutput>>::sign_transaction(
+            &mut ks_a, "CKB", &address_a, &tx_input,
+        )
+        .unwrap();
+        let partial_b = <Keystore as TransactionSigner<TxInput, PartialSignedTxOutput>>::sign_transaction(
+            &mut ks_b, "CKB", &address_b, &tx_input,
+        )
+        .unwrap();
+
+        // neither signer alone can finalize: each only fills its own slot.
+        assert!(partial_a.finalize().is_err());
+        assert!(partial_b.finalize().is_err());
+
+        let combined = ks_a.merge(&partial_a, &partial_b).unwrap();
+        let tx_output = combined.finalize().unwrap();
+
+        assert_eq!(tx_output.witnesses.len(), 1);
+        // script(S|R|M|N|hash*3) + 2 signature slots (threshold=2), packed
+        // densely even though the signers' raw indices are 0 and 2.
+        let script_len = 4 + 20 * 3;
+        assert_eq!(tx_output.witnesses[0].lock.len(), script_len + 65 * 2);
+        assert_ne!(tx_output.witnesses[0].lock, vec![] as Vec<u8>);
+    }
 }
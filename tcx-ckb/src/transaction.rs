@@ -0,0 +1,333 @@
+use crate::hash::blake2b_256;
+use crate::serializer::Serializer;
+use crate::Error;
+use tcx_chain::Result;
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct OutPoint {
+    #[prost(bytes, tag = "1")]
+    pub tx_hash: std::vec::Vec<u8>,
+    #[prost(uint32, tag = "2")]
+    pub index: u32,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CellInput {
+    #[prost(message, optional, tag = "1")]
+    pub previous_output: ::std::option::Option<OutPoint>,
+    #[prost(string, tag = "2")]
+    pub since: std::string::String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Script {
+    #[prost(bytes, tag = "1")]
+    pub args: std::vec::Vec<u8>,
+    #[prost(bytes, tag = "2")]
+    pub code_hash: std::vec::Vec<u8>,
+    #[prost(string, tag = "3")]
+    pub hash_type: std::string::String,
+}
+
+impl Script {
+    /// Molecule-serializes the script as `table { code_hash, hash_type, args }`
+    /// and hashes it with blake2b, producing the lock/type script hash used
+    /// to group inputs that share a script in `CkbTxSigner::group_script`.
+    pub fn to_hash(&self) -> Result<Vec<u8>> {
+        let hash_type_byte = Self::hash_type_to_byte(&self.hash_type)?;
+
+        let serialized = Serializer::serialize_dynamic_vec(&vec![
+            self.code_hash.clone(),
+            vec![hash_type_byte],
+            Serializer::serialize_fixed_vec(&vec![self.args.clone()]),
+        ]);
+
+        Ok(blake2b_256(&serialized).to_vec())
+    }
+
+    pub(crate) fn hash_type_to_byte(hash_type: &str) -> Result<u8> {
+        match hash_type {
+            "data" => Ok(0x00),
+            "type" => Ok(0x01),
+            "data1" => Ok(0x02),
+            _ => Err(Error::InvalidScriptHashType.into()),
+        }
+    }
+
+    pub(crate) fn hash_type_from_byte(byte: u8) -> Result<String> {
+        match byte {
+            0x00 => Ok("data".to_string()),
+            0x01 => Ok("type".to_string()),
+            0x02 => Ok("data1".to_string()),
+            _ => Err(Error::InvalidScriptHashType.into()),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CachedCell {
+    #[prost(string, tag = "1")]
+    pub derive_path: std::string::String,
+    #[prost(message, optional, tag = "2")]
+    pub r#type: ::std::option::Option<Script>,
+    #[prost(bytes, tag = "3")]
+    pub block_hash: std::vec::Vec<u8>,
+    #[prost(uint64, tag = "4")]
+    pub capacity: u64,
+    #[prost(message, optional, tag = "5")]
+    pub lock: ::std::option::Option<Script>,
+    #[prost(message, optional, tag = "6")]
+    pub out_point: ::std::option::Option<OutPoint>,
+    #[prost(bool, tag = "7")]
+    pub cellbase: bool,
+    #[prost(uint64, tag = "8")]
+    pub output_data_len: u64,
+    #[prost(string, tag = "9")]
+    pub status: std::string::String,
+    #[prost(bytes, tag = "10")]
+    pub data_hash: std::vec::Vec<u8>,
+    #[prost(message, optional, tag = "11")]
+    pub multisig_config: ::std::option::Option<MultisigConfig>,
+}
+
+/// M-of-N `secp256k1_blake160_multisig_all` lock config for a cell, mirroring
+/// the on-chain multisig script `S(version) | R(require_first_n) | M(threshold) | N(pubkey_count) | pubkey_hash{N}`.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct MultisigConfig {
+    #[prost(uint32, tag = "1")]
+    pub require_first_n: u32,
+    #[prost(uint32, tag = "2")]
+    pub threshold: u32,
+    /// 20-byte blake160 pubkey hashes, in the order they appear in the
+    /// serialized multisig script.
+    #[prost(bytes, repeated, tag = "3")]
+    pub pubkey_hashes: ::std::vec::Vec<std::vec::Vec<u8>>,
+}
+
+impl MultisigConfig {
+    /// Serializes the multisig script body: `S | R | M | N | pubkey_hash{N}`.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut script = vec![0x00u8, self.require_first_n as u8, self.threshold as u8];
+        script.push(self.pubkey_hashes.len() as u8);
+
+        for hash in self.pubkey_hashes.iter() {
+            script.extend_from_slice(hash);
+        }
+
+        script
+    }
+
+    /// Packs `filled` signatures into the witness lock: `script | signature{M}`.
+    /// The lock only ever reserves `threshold` (M) 65-byte slots, so `filled`
+    /// (each a `(pubkey_hashes` index, 65-byte signature)` pair) is sorted by
+    /// index and packed densely from slot 0 — the same relative order the
+    /// on-chain multisig lock verifies signatures against their pubkeys in.
+    pub fn pack_lock(&self, filled: &[(usize, Vec<u8>)]) -> Vec<u8> {
+        let mut ordered = filled.to_vec();
+        ordered.sort_by_key(|(index, _)| *index);
+
+        let script = self.serialize();
+        let script_len = script.len();
+
+        let mut lock = script;
+        lock.extend(vec![0u8; 65 * self.threshold as usize]);
+
+        for (position, (_, signature)) in ordered.iter().enumerate().take(self.threshold as usize) {
+            let offset = script_len + position * 65;
+            lock[offset..offset + 65].copy_from_slice(signature);
+        }
+
+        lock
+    }
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Witness {
+    #[prost(bytes, tag = "1")]
+    pub lock: std::vec::Vec<u8>,
+    #[prost(bytes, tag = "2")]
+    pub input_type: std::vec::Vec<u8>,
+    #[prost(bytes, tag = "3")]
+    pub output_type: std::vec::Vec<u8>,
+}
+
+impl Witness {
+    /// Serializes as the molecule `WitnessArgs` table, with `lock` wrapped
+    /// as a `Bytes` field since it is the only variable-length signature slot
+    /// the signer fills in; `input_type`/`output_type` are passed through raw.
+    pub fn serialize(&self) -> Vec<u8> {
+        Serializer::serialize_dynamic_vec(&vec![
+            Serializer::serialize_fixed_vec(&vec![self.lock.clone()]),
+            self.input_type.clone(),
+            self.output_type.clone(),
+        ])
+    }
+}
+
+#[derive(Clone, PartialEq, ::prost::Message, Default)]
+pub struct TxInput {
+    #[prost(message, repeated, tag = "1")]
+    pub inputs: ::std::vec::Vec<CellInput>,
+    #[prost(message, repeated, tag = "2")]
+    pub witnesses: ::std::vec::Vec<Witness>,
+    #[prost(bytes, tag = "3")]
+    pub tx_hash: std::vec::Vec<u8>,
+    #[prost(message, repeated, tag = "4")]
+    pub cached_cells: ::std::vec::Vec<CachedCell>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TxOutput {
+    #[prost(bytes, tag = "1")]
+    pub tx_hash: std::vec::Vec<u8>,
+    #[prost(message, repeated, tag = "2")]
+    pub witnesses: ::std::vec::Vec<Witness>,
+}
+
+/// One signature slot within a multisig witness lock: the pubkey hash it is
+/// reserved for, the `derive_path` and `signature` that filled it (once
+/// filled), and whether it still needs a co-signer. `slots` always has one
+/// entry per pubkey in `pubkey_hashes`, regardless of how many of those N
+/// pubkeys' slots the lock has physical room for (`threshold`, M) — the
+/// lock is rebuilt from whichever `signature`s are `filled` via
+/// `MultisigConfig::pack_lock`.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SignatureSlot {
+    #[prost(bytes, tag = "1")]
+    pub pubkey_hash: std::vec::Vec<u8>,
+    #[prost(string, tag = "2")]
+    pub derive_path: std::string::String,
+    #[prost(bool, tag = "3")]
+    pub filled: bool,
+    #[prost(bytes, tag = "4")]
+    pub signature: std::vec::Vec<u8>,
+}
+
+/// Bookkeeping for a single witness group's multisig lock, so that
+/// subsequent signing devices know which slots are still empty.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PartialWitness {
+    #[prost(uint32, tag = "1")]
+    pub witness_index: u32,
+    #[prost(bytes, tag = "2")]
+    pub lock: std::vec::Vec<u8>,
+    #[prost(message, repeated, tag = "3")]
+    pub slots: ::std::vec::Vec<SignatureSlot>,
+    #[prost(message, optional, tag = "4")]
+    pub multisig_config: ::std::option::Option<MultisigConfig>,
+}
+
+/// Intermediate result of signing a CKB transaction that has multisig
+/// witness groups still missing co-signers. Mirrors the BIP174 PSBT
+/// create/sign/combine roles: each signing device produces one of these,
+/// `combine` merges any number of them, and `finalize` emits the final
+/// `TxOutput` once every group has `threshold` of its slots filled.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PartialSignedTxOutput {
+    #[prost(bytes, tag = "1")]
+    pub tx_hash: std::vec::Vec<u8>,
+    #[prost(message, repeated, tag = "2")]
+    pub witnesses: ::std::vec::Vec<Witness>,
+    #[prost(message, repeated, tag = "3")]
+    pub partial_witnesses: ::std::vec::Vec<PartialWitness>,
+}
+
+fn filled_signatures(slots: &[SignatureSlot]) -> Vec<(usize, Vec<u8>)> {
+    slots
+        .iter()
+        .enumerate()
+        .filter(|(_, slot)| slot.filled)
+        .map(|(index, slot)| (index, slot.signature.clone()))
+        .collect()
+}
+
+impl PartialSignedTxOutput {
+    /// Merges the signature slots `other` has filled into `self`. Both must
+    /// have been produced for the same `tx_hash`; slots already filled in
+    /// `self` are left untouched even if `other` also signed them.
+    pub fn combine(&self, other: &PartialSignedTxOutput) -> Result<PartialSignedTxOutput> {
+        if self.tx_hash != other.tx_hash {
+            return Err(Error::PartialSignedTxHashMismatch.into());
+        }
+
+        let mut witnesses = self.witnesses.clone();
+        let mut partial_witnesses = vec![];
+
+        for group in self.partial_witnesses.iter() {
+            let other_group = other
+                .partial_witnesses
+                .iter()
+                .find(|g| g.witness_index == group.witness_index);
+
+            let merged = match other_group {
+                Some(other_group) => Self::merge_group(group, other_group)?,
+                None => group.clone(),
+            };
+
+            witnesses[merged.witness_index as usize].lock = merged.lock.clone();
+            partial_witnesses.push(merged);
+        }
+
+        Ok(PartialSignedTxOutput {
+            tx_hash: self.tx_hash.clone(),
+            witnesses,
+            partial_witnesses,
+        })
+    }
+
+    fn merge_group(a: &PartialWitness, b: &PartialWitness) -> Result<PartialWitness> {
+        let config = a
+            .multisig_config
+            .as_ref()
+            .ok_or(Error::MultisigConfigMissing)?;
+
+        let slots = a
+            .slots
+            .iter()
+            .zip(b.slots.iter())
+            .map(|(slot, other_slot)| {
+                let filled_by_other = !slot.filled && other_slot.filled;
+                if filled_by_other {
+                    SignatureSlot {
+                        pubkey_hash: slot.pubkey_hash.clone(),
+                        derive_path: other_slot.derive_path.clone(),
+                        filled: true,
+                        signature: other_slot.signature.clone(),
+                    }
+                } else {
+                    slot.clone()
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let lock = config.pack_lock(&filled_signatures(&slots));
+
+        Ok(PartialWitness {
+            witness_index: a.witness_index,
+            lock,
+            slots,
+            multisig_config: a.multisig_config.clone(),
+        })
+    }
+
+    /// Emits the final `TxOutput` once every witness group has at least
+    /// `threshold` (M) of its N slots filled — an M-of-N multisig only ever
+    /// needs M signatures, not all N; errors if any group is still short.
+    pub fn finalize(&self) -> Result<TxOutput> {
+        for group in self.partial_witnesses.iter() {
+            let config = group
+                .multisig_config
+                .as_ref()
+                .ok_or(Error::MultisigConfigMissing)?;
+
+            if filled_signatures(&group.slots).len() < config.threshold as usize {
+                return Err(Error::PartialSignatureIncomplete.into());
+            }
+        }
+
+        Ok(TxOutput {
+            tx_hash: self.tx_hash.clone(),
+            witnesses: self.witnesses.clone(),
+        })
+    }
+}
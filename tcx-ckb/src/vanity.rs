@@ -0,0 +1,162 @@
+//! Support for mining vanity CKB addresses: scan HD child indices under a
+//! derivation path, bounded by a max iteration count, for the first address
+//! matching a prefix/suffix pattern.
+
+use crate::Error;
+use tcx_chain::{Account, Address, Keystore, Result};
+use tcx_constants::CoinInfo;
+
+/// Default cap on how many HD child indices `scan_vanity_address` will
+/// derive before giving up, for callers that don't need a tighter bound.
+pub const DEFAULT_MAX_ITERATIONS: u32 = 1_000_000;
+
+/// Returns true if `address` matches the requested `prefix`/`suffix` (at
+/// least one must be given) over the address's bech32 charset, optionally
+/// case-insensitively. Mirrors the prefix matching ethkey's `BrainPrefix`
+/// vanity key generator does over hex addresses.
+pub fn matches_pattern(
+    address: &str,
+    prefix: Option<&str>,
+    suffix: Option<&str>,
+    case_insensitive: bool,
+) -> bool {
+    if prefix.is_none() && suffix.is_none() {
+        return false;
+    }
+
+    let normalize = |s: &str| -> String {
+        if case_insensitive {
+            s.to_lowercase()
+        } else {
+            s.to_string()
+        }
+    };
+
+    let candidate = normalize(address);
+
+    let prefix_ok = prefix.map_or(true, |p| candidate.starts_with(&normalize(p)));
+    let suffix_ok = suffix.map_or(true, |s| candidate.ends_with(&normalize(s)));
+
+    prefix_ok && suffix_ok
+}
+
+/// Mines a vanity address by scanning successive derivation indices of a
+/// `Keystore`, checking each derived account's address against `prefix`
+/// and/or `suffix` via `matches_pattern`. Generic over `A` so it works for
+/// any coin whose address derivation goes through the `Address` trait, not
+/// just CKB's.
+pub trait VanityScan<A> {
+    /// Derives child accounts at `coin_info.derivation_path`/0, /1, /2, ...
+    /// until one matches `prefix`/`suffix`, or returns
+    /// `Error::VanityPatternNotFound` after `max_iterations` misses. Returns
+    /// the matching account together with the derivation path it was found
+    /// at.
+    fn scan_vanity_address(
+        &mut self,
+        coin_info: &CoinInfo,
+        prefix: Option<&str>,
+        suffix: Option<&str>,
+        case_insensitive: bool,
+        max_iterations: u32,
+    ) -> Result<(String, Account)>;
+}
+
+impl<A: Address> VanityScan<A> for Keystore {
+    fn scan_vanity_address(
+        &mut self,
+        coin_info: &CoinInfo,
+        prefix: Option<&str>,
+        suffix: Option<&str>,
+        case_insensitive: bool,
+        max_iterations: u32,
+    ) -> Result<(String, Account)> {
+        for index in 0..max_iterations {
+            let mut candidate = coin_info.clone();
+            candidate.derivation_path = format!("{}/{}", coin_info.derivation_path, index);
+
+            let account = self.derive_coin::<A>(&candidate)?.clone();
+            if matches_pattern(&account.address, prefix, suffix, case_insensitive) {
+                return Ok((candidate.derivation_path, account));
+            }
+        }
+
+        Err(Error::VanityPatternNotFound.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{matches_pattern, VanityScan};
+    use crate::address::CkbAddress;
+    use tcx_chain::Keystore;
+    use tcx_constants::{CoinInfo, CurveType};
+
+    #[test]
+    fn matches_prefix_and_suffix() {
+        let address = "ckt1qyqvsv5240xeefr8eywupw5n0nnur6acv8xcmfhss5";
+
+        assert!(matches_pattern(address, Some("ckt1qyq"), None, false));
+        assert!(matches_pattern(address, None, Some("hss5"), false));
+        assert!(matches_pattern(address, Some("CKT1QYQ"), None, true));
+        assert!(!matches_pattern(address, Some("CKT1QYQ"), None, false));
+        assert!(!matches_pattern(address, Some("nope"), None, false));
+    }
+
+    #[test]
+    fn requires_at_least_one_pattern() {
+        assert!(!matches_pattern("ckt1qyqvsv5240xeefr8eywupw5n0nnur6acv8xcmfhss5", None, None, false));
+    }
+
+    #[test]
+    fn scan_finds_matching_child_address() {
+        let mut ks = Keystore::from_private_key(
+            "dcec27d0d975b0378471183a03f7071dea8532aaf968be796719ecd20af6988f",
+            "Password",
+        );
+        ks.unlock_by_password("Password");
+
+        let coin_info = CoinInfo {
+            coin: "CKB".to_string(),
+            derivation_path: "m/44'/309'/0'".to_string(),
+            curve: CurveType::SECP256k1,
+            network: "TESTNET".to_string(),
+            seg_wit: "".to_string(),
+        };
+
+        // every CKB testnet short address shares this prefix, so even a
+        // one-index bound finds a "match" right away.
+        let (path, account) = ks
+            .scan_vanity_address::<CkbAddress>(&coin_info, Some("ckt1"), None, false, 1)
+            .unwrap();
+
+        assert_eq!(path, "m/44'/309'/0'/0");
+        assert!(matches_pattern(&account.address, Some("ckt1"), None, false));
+    }
+
+    #[test]
+    fn scan_gives_up_after_max_iterations_for_an_impossible_pattern() {
+        let mut ks = Keystore::from_private_key(
+            "dcec27d0d975b0378471183a03f7071dea8532aaf968be796719ecd20af6988f",
+            "Password",
+        );
+        ks.unlock_by_password("Password");
+
+        let coin_info = CoinInfo {
+            coin: "CKB".to_string(),
+            derivation_path: "m/44'/309'/0'".to_string(),
+            curve: CurveType::SECP256k1,
+            network: "TESTNET".to_string(),
+            seg_wit: "".to_string(),
+        };
+
+        let result = ks.scan_vanity_address::<CkbAddress>(
+            &coin_info,
+            Some("ckt1qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqq"),
+            None,
+            false,
+            8,
+        );
+
+        assert!(result.is_err());
+    }
+}